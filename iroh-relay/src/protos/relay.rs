@@ -427,6 +427,8 @@ impl ClientToRelayMsg {
 
         let res = match frame_type {
             FrameType::ClientToRelayDatagram | FrameType::ClientToRelayDatagramBatch => {
+                snafu::ensure!(content.len() >= NodeId::LENGTH, InvalidFrameSnafu);
+
                 let dst_node_id = cache
                     .key_from_slice(&content[..NodeId::LENGTH])
                     .context(InvalidPublicKeySnafu)?;
@@ -710,6 +712,22 @@ mod proptests {
     }
 
     proptest! {
+        #[test]
+        fn server_client_frame_decode_arbitrary(frame_type in any::<u8>(), content in prop::collection::vec(any::<u8>(), 0..128)) {
+            let mut bytes = vec![frame_type];
+            bytes.extend(content);
+            // Must not panic, the result does not matter.
+            RelayToClientMsg::from_bytes(Bytes::from(bytes), &KeyCache::test()).ok();
+        }
+
+        #[test]
+        fn client_server_frame_decode_arbitrary(frame_type in any::<u8>(), content in prop::collection::vec(any::<u8>(), 0..128)) {
+            let mut bytes = vec![frame_type];
+            bytes.extend(content);
+            // Must not panic, the result does not matter.
+            ClientToRelayMsg::from_bytes(Bytes::from(bytes), &KeyCache::test()).ok();
+        }
+
         #[test]
         fn server_client_frame_roundtrip(frame in server_client_frame()) {
             let encoded = frame.to_bytes().freeze();
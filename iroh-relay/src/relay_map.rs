@@ -51,6 +51,16 @@ impl RelayMap {
     pub fn is_empty(&self) -> bool {
         self.nodes.is_empty()
     }
+
+    /// Inserts a new node, replacing and returning any node with the same URL.
+    pub fn insert(&mut self, node: RelayNode) -> Option<Arc<RelayNode>> {
+        Arc::make_mut(&mut self.nodes).insert(node.url.clone(), Arc::new(node))
+    }
+
+    /// Removes the node with the given URL, returning it if it was present.
+    pub fn remove(&mut self, url: &RelayUrl) -> Option<Arc<RelayNode>> {
+        Arc::make_mut(&mut self.nodes).remove(url)
+    }
 }
 
 impl FromIterator<RelayNode> for RelayMap {
@@ -158,3 +168,23 @@ impl fmt::Display for RelayNode {
         write!(f, "{}", self.url)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_remove_does_not_change_clones() {
+        let url_a: RelayUrl = "https://a.example".parse().unwrap();
+        let url_b: RelayUrl = "https://b.example".parse().unwrap();
+        let mut map = RelayMap::from(url_a.clone());
+        let copy = map.clone();
+
+        assert!(map.insert(url_b.clone().into()).is_none());
+        assert!(map.remove(&url_a).is_some());
+        assert!(map.remove(&url_a).is_none());
+
+        assert_eq!(map.urls().collect::<Vec<_>>(), vec![&url_b]);
+        assert_eq!(copy.urls().collect::<Vec<_>>(), vec![&url_a]);
+    }
+}
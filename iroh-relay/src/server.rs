@@ -14,6 +14,7 @@
 //! - HTTPS `/relay`: The main URL endpoint to which clients connect and sends traffic over.
 //! - HTTPS `/ping`: Used for net_report probes.
 //! - HTTPS `/generate_204`: Used for net_report probes.
+//! - HTTPS `/healthz`: Used by load balancers and service monitors for liveness checks.
 
 use std::{fmt, future::Future, net::SocketAddr, num::NonZeroU32, pin::Pin, sync::Arc};
 
@@ -377,7 +378,8 @@ impl Server {
                     .request_handler(Method::GET, "/", Box::new(root_handler))
                     .request_handler(Method::GET, "/index.html", Box::new(root_handler))
                     .request_handler(Method::GET, RELAY_PROBE_PATH, Box::new(probe_handler))
-                    .request_handler(Method::GET, "/robots.txt", Box::new(robots_handler));
+                    .request_handler(Method::GET, "/robots.txt", Box::new(robots_handler))
+                    .request_handler(Method::GET, "/healthz", Box::new(healthz_handler));
                 if let Some(cfg) = relay_config.limits.client_rx {
                     builder = builder.client_rx_ratelimit(cfg);
                 }
@@ -635,6 +637,18 @@ fn robots_handler(
         .map_err(|err| Box::new(err) as HyperError)
 }
 
+/// Liveness checks, responds as long as the relay server is serving requests.
+fn healthz_handler(
+    _r: Request<Incoming>,
+    response: ResponseBuilder,
+) -> HyperResult<Response<BytesBody>> {
+    response
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/plain; charset=utf-8")
+        .body("OK".into())
+        .map_err(|err| Box::new(err) as HyperError)
+}
+
 /// For captive portal detection.
 fn serve_no_content_handler<B: hyper::body::Body>(
     r: Request<B>,
@@ -862,6 +876,18 @@ mod tests {
         assert!(body.contains("iroh.computer"));
     }
 
+    #[tokio::test]
+    #[traced_test]
+    async fn test_healthz_handler() {
+        let server = spawn_local_relay().await.unwrap();
+        let url = format!("http://{}/healthz", server.http_addr().unwrap());
+
+        let response = reqwest::get(&url).await.unwrap();
+        assert_eq!(response.status(), 200);
+        let body = response.text().await.unwrap();
+        assert_eq!(body, "OK");
+    }
+
     #[tokio::test]
     #[traced_test]
     async fn test_captive_portal_service() {
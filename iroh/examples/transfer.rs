@@ -40,6 +40,8 @@ const DEV_DNS_SERVER: &str = "127.0.0.1:5300";
 ///
 /// --relay-only needs the `test-utils` feature
 ///
+/// --simulate needs the `test-utils` feature
+///
 /// --mdns needs the `discovery-local-network` feature
 ///
 /// To enable all features, run the example with --all-features:
@@ -132,6 +134,10 @@ struct EndpointArgs {
     #[clap(long)]
     /// Enable mDNS discovery.
     mdns: bool,
+    /// Simulate a bad network on received data, e.g. `latency=100ms,jitter=10ms,loss=2%`.
+    #[cfg(feature = "test-utils")]
+    #[clap(long)]
+    simulate: Option<iroh::endpoint::NetworkConditions>,
 }
 
 #[derive(Subcommand, Debug)]
@@ -227,6 +233,11 @@ impl EndpointArgs {
             builder = builder.path_selection(iroh::endpoint::PathSelection::RelayOnly)
         }
 
+        #[cfg(feature = "test-utils")]
+        if let Some(conditions) = self.simulate {
+            builder = builder.network_conditions(conditions);
+        }
+
         if let Some(host) = self.dns_server {
             let addr = tokio::net::lookup_host(host)
                 .await
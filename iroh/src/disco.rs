@@ -313,7 +313,8 @@ fn socket_addr_as_bytes(addr: &SocketAddr) -> [u8; EP_LENGTH] {
 
 impl Pong {
     fn from_bytes(p: &[u8]) -> Result<Self, ParseError> {
-        let tx_id: [u8; TX_LEN] = p[..TX_LEN].try_into().map_err(|_| TooShortSnafu.build())?;
+        ensure!(p.len() >= TX_LEN, TooShortSnafu);
+        let tx_id: [u8; TX_LEN] = p[..TX_LEN].try_into().expect("length checked");
 
         let tx_id = stun_rs::TransactionId::from(tx_id);
         let src = send_addr_from_bytes(&p[TX_LEN..])?;
@@ -509,6 +510,36 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_from_bytes_truncated() {
+        let messages = [
+            Message::Ping(Ping {
+                tx_id: stun_rs::TransactionId::default(),
+                node_key: SecretKey::generate(rand::thread_rng()).public(),
+            }),
+            Message::Pong(Pong {
+                tx_id: stun_rs::TransactionId::default(),
+                ping_observed_addr: SendAddr::Udp("[fed0::12]:6666".parse().unwrap()),
+            }),
+            Message::Pong(Pong {
+                tx_id: stun_rs::TransactionId::default(),
+                ping_observed_addr: SendAddr::Relay("https://relay.example".parse().unwrap()),
+            }),
+            Message::CallMeMaybe(CallMeMaybe {
+                my_numbers: vec!["1.2.3.4:567".parse().unwrap()],
+            }),
+        ];
+        for msg in messages {
+            let bytes = msg.as_bytes();
+            // Truncated messages may still parse, e.g. a shorter relay URL, but must never
+            // panic.
+            for len in 0..bytes.len() {
+                Message::from_bytes(&bytes[..len]).ok();
+            }
+            assert_eq!(Message::from_bytes(&bytes).unwrap(), msg);
+        }
+    }
+
     #[test]
     fn test_extraction() {
         let sender_key = SecretKey::generate(rand::thread_rng());
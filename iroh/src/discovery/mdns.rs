@@ -127,21 +127,47 @@ impl Subscribers {
 
 /// Builder for [`MdnsDiscovery`].
 #[derive(Debug)]
-pub struct MdnsDiscoveryBuilder;
+pub struct MdnsDiscoveryBuilder {
+    advertise: bool,
+}
+
+impl MdnsDiscoveryBuilder {
+    /// Creates a new builder, which advertises this node by default.
+    pub fn new() -> Self {
+        Self { advertise: true }
+    }
+
+    /// Sets whether this node advertises its addresses on the local network.
+    ///
+    /// When disabled the node still discovers other nodes on the local network, but other
+    /// nodes can not discover it.  This is useful for privacy-sensitive deployments.
+    ///
+    /// Defaults to `true`.
+    pub fn advertise(mut self, advertise: bool) -> Self {
+        self.advertise = advertise;
+        self
+    }
+}
+
+impl Default for MdnsDiscoveryBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl IntoDiscovery for MdnsDiscoveryBuilder {
     fn into_discovery(
         self,
         context: &DiscoveryContext,
     ) -> Result<impl Discovery, IntoDiscoveryError> {
-        MdnsDiscovery::new(context.node_id())
+        MdnsDiscovery::new_with_advertise(context.node_id(), self.advertise)
     }
 }
 
 impl MdnsDiscovery {
     /// Returns a [`MdnsDiscoveryBuilder`] that implements [`IntoDiscovery`].
     pub fn builder() -> MdnsDiscoveryBuilder {
-        MdnsDiscoveryBuilder
+        MdnsDiscoveryBuilder::new()
     }
 
     /// Create a new [`MdnsDiscovery`] Service.
@@ -154,6 +180,11 @@ impl MdnsDiscovery {
     /// # Panics
     /// This relies on [`tokio::runtime::Handle::current`] and will panic if called outside of the context of a tokio runtime.
     pub fn new(node_id: NodeId) -> Result<Self, IntoDiscoveryError> {
+        Self::new_with_advertise(node_id, true)
+    }
+
+    /// Like [`MdnsDiscovery::new`], but only broadcasts your addresses if `advertise` is `true`.
+    fn new_with_advertise(node_id: NodeId, advertise: bool) -> Result<Self, IntoDiscoveryError> {
         debug!("Creating new MdnsDiscovery service");
         let (send, mut recv) = mpsc::channel(64);
         let task_sender = send.clone();
@@ -178,7 +209,7 @@ impl MdnsDiscovery {
                     msg = recv.recv() => {
                         msg
                     }
-                    Ok(Some(data)) = addrs_change.updated() => {
+                    Ok(Some(data)) = addrs_change.updated(), if advertise => {
                         tracing::trace!(?data, "MdnsDiscovery address changed");
                         discovery.remove_all();
                         let addrs =
@@ -513,9 +544,43 @@ mod tests {
                 .context("timeout")?
         }
 
+        #[tokio::test]
+        #[traced_test]
+        async fn mdns_no_advertise() -> Result {
+            let (node_id_a, discovery_a) = make_discoverer()?;
+            let (node_id_b, discovery_b) = make_discoverer_with(false)?;
+
+            let node_data = NodeData::new(None, BTreeSet::from(["0.0.0.0:11111".parse().unwrap()]));
+            discovery_a.publish(&node_data);
+            discovery_b.publish(&node_data);
+
+            // b still discovers a
+            let mut s1 = discovery_b.resolve(node_id_a).unwrap();
+            let res = tokio::time::timeout(Duration::from_secs(5), s1.next())
+                .await
+                .context("timeout")?
+                .unwrap()?;
+            assert_eq!(res.node_info().data, node_data);
+
+            // but b is not discovered by a
+            let mut s2 = discovery_a.resolve(node_id_b).unwrap();
+            let res = tokio::time::timeout(Duration::from_secs(2), s2.next()).await;
+            assert!(res.is_err(), "unexpectedly discovered b: {res:?}");
+
+            Ok(())
+        }
+
         fn make_discoverer() -> Result<(PublicKey, MdnsDiscovery)> {
             let node_id = SecretKey::generate(rand::thread_rng()).public();
             Ok((node_id, MdnsDiscovery::new(node_id)?))
         }
+
+        fn make_discoverer_with(advertise: bool) -> Result<(PublicKey, MdnsDiscovery)> {
+            let node_id = SecretKey::generate(rand::thread_rng()).public();
+            Ok((
+                node_id,
+                MdnsDiscovery::new_with_advertise(node_id, advertise)?,
+            ))
+        }
     }
 }
@@ -348,10 +348,8 @@ impl PublisherService {
         let mut failed_attempts = 0;
         let republish = time::sleep(Duration::MAX);
         tokio::pin!(republish);
-        loop {
-            let Ok(info) = self.watcher.get() else {
-                break; // disconnected
-            };
+        // Stops once the watcher is disconnected.
+        while let Ok(info) = self.watcher.get() {
             if let Some(info) = info {
                 match self.publish_current(info).await {
                     Err(err) => {
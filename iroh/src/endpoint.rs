@@ -23,7 +23,7 @@ use std::{
 
 use ed25519_dalek::{VerifyingKey, pkcs8::DecodePublicKey};
 use iroh_base::{NodeAddr, NodeId, RelayUrl, SecretKey};
-use iroh_relay::RelayMap;
+use iroh_relay::{RelayMap, RelayNode};
 use n0_future::{Stream, time::Duration};
 use n0_watcher::Watcher;
 use nested_enum_utils::common_fields;
@@ -72,6 +72,9 @@ pub use super::magicsock::{
     AddNodeAddrError, ConnectionType, ControlMsg, DirectAddr, DirectAddrInfo, DirectAddrType,
     RemoteInfo, Source,
 };
+/// Configuration of the port mapping protocols, see [`Builder::portmapper_config`].
+#[cfg(not(wasm_browser))]
+pub use portmapper::Config as PortmapperConfig;
 
 /// The delay to fall back to discovery when direct addresses fail.
 ///
@@ -92,6 +95,108 @@ pub enum PathSelection {
     RelayOnly,
 }
 
+/// Artificial network conditions to simulate on an endpoint.
+///
+/// The conditions are applied to every datagram received by the endpoint, regardless of
+/// whether it arrived directly or via a relay server.  To simulate a degraded link in both
+/// directions configure both endpoints of a connection.
+///
+/// See [`Builder::network_conditions`].
+#[cfg(any(test, feature = "test-utils"))]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct NetworkConditions {
+    /// Fixed delay added to every received datagram.
+    pub latency: Duration,
+    /// Upper bound of an additional, uniformly distributed, random delay.
+    ///
+    /// Because each datagram is delayed independently, jitter can cause datagrams to be
+    /// delivered out of order.
+    pub jitter: Duration,
+    /// Probability, between `0.0` and `1.0`, with which a received datagram is dropped.
+    ///
+    /// Values outside this range are clamped, NaN is treated as `0.0`.
+    pub loss: f64,
+    /// Seed for the random number generator used for jitter and loss.
+    ///
+    /// Setting this makes the dropped datagrams and applied delays reproducible, if unset
+    /// the generator is seeded from the operating system.
+    pub seed: Option<u64>,
+}
+
+/// Parses [`NetworkConditions`] from a comma separated list of `key=value` pairs.
+///
+/// The keys are `latency` and `jitter`, taking a duration such as `100ms` or `1.5s`, `loss`,
+/// taking a percentage such as `2%` or a fraction such as `0.02`, and `seed`.  Omitted keys
+/// keep their default, e.g. `latency=100ms,loss=2%`.
+#[cfg(any(test, feature = "test-utils"))]
+impl std::str::FromStr for NetworkConditions {
+    type Err = ParseNetworkConditionsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut conditions = Self::default();
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            let Some((key, value)) = part.split_once('=') else {
+                return Err(parse_network_conditions_error::MissingValueSnafu { part }.build());
+            };
+            let (key, value) = (key.trim(), value.trim());
+            let invalid =
+                || parse_network_conditions_error::InvalidValueSnafu { key, value }.build();
+            match key {
+                "latency" => conditions.latency = parse_duration(value).ok_or_else(invalid)?,
+                "jitter" => conditions.jitter = parse_duration(value).ok_or_else(invalid)?,
+                "loss" => conditions.loss = parse_loss(value).ok_or_else(invalid)?,
+                "seed" => conditions.seed = Some(value.parse().map_err(|_| invalid())?),
+                _ => return Err(parse_network_conditions_error::UnknownKeySnafu { key }.build()),
+            }
+        }
+        Ok(conditions)
+    }
+}
+
+/// Parses a non-negative duration with a `us`, `ms` or `s` unit.
+#[cfg(any(test, feature = "test-utils"))]
+fn parse_duration(s: &str) -> Option<Duration> {
+    let (number, unit_secs) = if let Some(number) = s.strip_suffix("us") {
+        (number, 1e-6)
+    } else if let Some(number) = s.strip_suffix("ms") {
+        (number, 1e-3)
+    } else {
+        (s.strip_suffix('s')?, 1.0)
+    };
+    let number: f64 = number.trim().parse().ok()?;
+    Duration::try_from_secs_f64(number * unit_secs).ok()
+}
+
+/// Parses a loss probability given as a percentage or as a fraction.
+#[cfg(any(test, feature = "test-utils"))]
+fn parse_loss(s: &str) -> Option<f64> {
+    let loss = match s.strip_suffix('%') {
+        Some(percent) => percent.trim().parse::<f64>().ok()? / 100.0,
+        None => s.parse().ok()?,
+    };
+    (0.0..=1.0).contains(&loss).then_some(loss)
+}
+
+/// Error when parsing [`NetworkConditions`] from a string.
+#[cfg(any(test, feature = "test-utils"))]
+#[allow(missing_docs)]
+#[common_fields({
+    backtrace: Option<snafu::Backtrace>,
+    #[snafu(implicit)]
+    span_trace: n0_snafu::SpanTrace,
+})]
+#[derive(Debug, Snafu)]
+#[snafu(module)]
+#[non_exhaustive]
+pub enum ParseNetworkConditionsError {
+    #[snafu(display("Expected `key=value`, found `{part}`"))]
+    MissingValue { part: String },
+    #[snafu(display("Unknown network condition `{key}`"))]
+    UnknownKey { key: String },
+    #[snafu(display("Invalid value `{value}` for `{key}`"))]
+    InvalidValue { key: String, value: String },
+}
+
 /// Builder for [`Endpoint`].
 ///
 /// By default the endpoint will generate a new random [`SecretKey`], which will result in a
@@ -112,12 +217,18 @@ pub struct Builder {
     node_map: Option<Vec<NodeAddr>>,
     #[cfg(not(wasm_browser))]
     dns_resolver: Option<DnsResolver>,
+    #[cfg(not(wasm_browser))]
+    portmapper_config: PortmapperConfig,
+    #[cfg(not(wasm_browser))]
+    advertised_addrs: Vec<SocketAddr>,
     #[cfg(any(test, feature = "test-utils"))]
     insecure_skip_relay_cert_verify: bool,
     addr_v4: Option<SocketAddrV4>,
     addr_v6: Option<SocketAddrV6>,
     #[cfg(any(test, feature = "test-utils"))]
     path_selection: PathSelection,
+    #[cfg(any(test, feature = "test-utils"))]
+    network_conditions: Option<NetworkConditions>,
 }
 
 impl Default for Builder {
@@ -136,12 +247,18 @@ impl Default for Builder {
             node_map: None,
             #[cfg(not(wasm_browser))]
             dns_resolver: None,
+            #[cfg(not(wasm_browser))]
+            portmapper_config: PortmapperConfig::default(),
+            #[cfg(not(wasm_browser))]
+            advertised_addrs: Vec::new(),
             #[cfg(any(test, feature = "test-utils"))]
             insecure_skip_relay_cert_verify: false,
             addr_v4: None,
             addr_v6: None,
             #[cfg(any(test, feature = "test-utils"))]
             path_selection: PathSelection::default(),
+            #[cfg(any(test, feature = "test-utils"))]
+            network_conditions: None,
         }
     }
 }
@@ -199,11 +316,17 @@ impl Builder {
             proxy_url: self.proxy_url,
             #[cfg(not(wasm_browser))]
             dns_resolver,
+            #[cfg(not(wasm_browser))]
+            portmapper_config: self.portmapper_config,
+            #[cfg(not(wasm_browser))]
+            advertised_addrs: self.advertised_addrs,
             server_config,
             #[cfg(any(test, feature = "test-utils"))]
             insecure_skip_relay_cert_verify: self.insecure_skip_relay_cert_verify,
             #[cfg(any(test, feature = "test-utils"))]
             path_selection: self.path_selection,
+            #[cfg(any(test, feature = "test-utils"))]
+            network_conditions: self.network_conditions,
             metrics,
         };
 
@@ -446,6 +569,33 @@ impl Builder {
         self
     }
 
+    /// Configures which port mapping protocols are used.
+    ///
+    /// The endpoint tries to create a port mapping on the local gateway using UPnP, PCP or
+    /// NAT-PMP, so that it can be reached directly from the internet.  A successfully
+    /// mapped address is reported as a [`DirectAddrType::Portmapped`] direct address.
+    ///
+    /// By default all protocols are enabled.  Disabling all of them turns off port mapping
+    /// entirely.
+    #[cfg(not(wasm_browser))]
+    pub fn portmapper_config(mut self, config: PortmapperConfig) -> Self {
+        self.portmapper_config = config;
+        self
+    }
+
+    /// Sets addresses to always advertise as direct addresses of this endpoint.
+    ///
+    /// Use this when the endpoint is known to be reachable on an address it can not
+    /// discover itself, e.g. a stable public IP address or a port manually forwarded on the
+    /// router.  These addresses are included in the [`NodeAddr`] and published to discovery
+    /// services as [`DirectAddrType::Advertised`] direct addresses, in addition to the
+    /// addresses discovered by the endpoint.
+    #[cfg(not(wasm_browser))]
+    pub fn advertised_addrs(mut self, addrs: impl IntoIterator<Item = SocketAddr>) -> Self {
+        self.advertised_addrs = addrs.into_iter().collect();
+        self
+    }
+
     /// Enables saving the TLS pre-master key for connections.
     ///
     /// This key should normally remain secret but can be useful to debug networking issues
@@ -474,6 +624,19 @@ impl Builder {
         self.path_selection = path_selection;
         self
     }
+
+    /// Simulates adverse network conditions for all traffic received by this endpoint.
+    ///
+    /// This adds latency, jitter and packet loss to the datagrams received by the
+    /// endpoint, allowing to test the behaviour of applications on bad networks using only
+    /// a single machine.
+    ///
+    /// May only be used in tests.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub fn network_conditions(mut self, network_conditions: NetworkConditions) -> Self {
+        self.network_conditions = Some(network_conditions);
+        self
+    }
 }
 
 /// Configuration for a [`quinn::Endpoint`] that cannot be changed at runtime.
@@ -980,6 +1143,32 @@ impl Endpoint {
         self.msock.home_relay()
     }
 
+    /// Adds a relay server to the relay servers this endpoint uses.
+    ///
+    /// If a relay server with the same [`RelayUrl`] is already used, it is replaced and
+    /// returned.  A new net_report is started right away, so the new relay server can be
+    /// chosen as home relay if it has the lowest latency.
+    ///
+    /// Together with [`Endpoint::remove_relay`] this allows updating the relay servers
+    /// configured with [`Builder::relay_mode`] without restarting the endpoint, e.g. from a
+    /// relay map that is periodically fetched from elsewhere.  Only this endpoint is
+    /// changed, not the [`RelayMap`] it was built from or other endpoints using it.
+    pub async fn insert_relay(&self, relay: RelayNode) -> Option<Arc<RelayNode>> {
+        self.msock.insert_relay(relay).await
+    }
+
+    /// Removes a relay server from the relay servers this endpoint uses.
+    ///
+    /// Returns the removed relay server, if it was used.  If it was the home relay, a new
+    /// home relay is chosen from the remaining relay servers.  Removing the last relay
+    /// server leaves the endpoint without a home relay.
+    ///
+    /// Existing connections to other nodes that use this relay server as their home relay
+    /// are not affected.
+    pub async fn remove_relay(&self, url: &RelayUrl) -> Option<Arc<RelayNode>> {
+        self.msock.remove_relay(url).await
+    }
+
     /// Returns a [`Watcher`] for the direct addresses of this [`Endpoint`].
     ///
     /// The direct addresses of the [`Endpoint`] are those that could be used by other
@@ -2264,7 +2453,10 @@ mod tests {
     use super::Endpoint;
     use crate::{
         RelayMode,
-        endpoint::{ConnectOptions, Connection, ConnectionType, RemoteInfo},
+        endpoint::{
+            ConnectOptions, Connection, ConnectionType, DirectAddr, DirectAddrType,
+            NetworkConditions, PortmapperConfig, RemoteInfo,
+        },
         test_utils::{run_relay_server, run_relay_server_with},
     };
 
@@ -3181,6 +3373,298 @@ mod tests {
         Ok(())
     }
 
+    /// Maximum message size for [`echo_with_network_conditions`].
+    const ECHO_LIMIT: usize = 1024 * 1024;
+
+    /// Echoes `msg` over a connection between two endpoints with the given network conditions.
+    async fn echo_with_network_conditions(
+        conditions: NetworkConditions,
+        msg: &[u8],
+    ) -> Result<Connection> {
+        let server = Endpoint::builder()
+            .relay_mode(RelayMode::Disabled)
+            .alpns(vec![TEST_ALPN.to_vec()])
+            .network_conditions(conditions.clone())
+            .bind()
+            .await?;
+        let client = Endpoint::builder()
+            .relay_mode(RelayMode::Disabled)
+            .network_conditions(conditions)
+            .bind()
+            .await?;
+        let server_addr = server.node_addr().initialized().await?;
+        let server_task = tokio::spawn(async move {
+            let conn = server.accept().await.e()?.await.e()?;
+            let (mut send, mut recv) = conn.accept_bi().await.e()?;
+            let msg = recv.read_to_end(ECHO_LIMIT).await.e()?;
+            send.write_all(&msg).await.e()?;
+            send.finish().e()?;
+            conn.closed().await;
+            Ok::<_, Error>(())
+        });
+
+        let conn = client.connect(server_addr, TEST_ALPN).await?;
+        let (mut send, mut recv) = conn.open_bi().await.e()?;
+        send.write_all(msg).await.e()?;
+        send.finish().e()?;
+        let echo = recv.read_to_end(ECHO_LIMIT).await.e()?;
+        assert_eq!(echo, msg);
+        conn.close(0u32.into(), b"done");
+        server_task.await.e()??;
+        Ok(conn)
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn network_conditions_latency() -> Result {
+        let conditions = NetworkConditions {
+            latency: Duration::from_millis(50),
+            jitter: Duration::from_millis(10),
+            seed: Some(0),
+            ..Default::default()
+        };
+        let conn = echo_with_network_conditions(conditions, b"hello").await?;
+        // Both endpoints delay received datagrams, so each round trip is delayed twice.
+        assert!(
+            conn.rtt() >= Duration::from_millis(100),
+            "rtt {:?}",
+            conn.rtt()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn network_conditions_loss() -> Result {
+        let conditions = NetworkConditions {
+            loss: 0.2,
+            seed: Some(42),
+            ..Default::default()
+        };
+        let msg = vec![0xab; 256 * 1024];
+        let conn = echo_with_network_conditions(conditions, &msg).await?;
+        assert!(conn.stats().path.lost_packets > 0);
+        Ok(())
+    }
+
+    #[test]
+    fn network_conditions_from_str() {
+        let conditions: NetworkConditions =
+            "latency=100ms, jitter=1.5s,loss=2%,seed=7".parse().unwrap();
+        assert_eq!(
+            conditions,
+            NetworkConditions {
+                latency: Duration::from_millis(100),
+                jitter: Duration::from_millis(1500),
+                loss: 0.02,
+                seed: Some(7),
+            }
+        );
+        let conditions: NetworkConditions = "loss=0.5".parse().unwrap();
+        assert_eq!(conditions.loss, 0.5);
+        assert_eq!(conditions.latency, Duration::ZERO);
+        assert_eq!("".parse::<NetworkConditions>().unwrap(), Default::default());
+
+        for invalid in [
+            "latency",
+            "latency=100",
+            "latency=-1ms",
+            "loss=200%",
+            "seed=x",
+            "bandwidth=1mbit",
+        ] {
+            assert!(invalid.parse::<NetworkConditions>().is_err(), "{invalid}");
+        }
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn endpoint_update_relays() -> Result {
+        let (relay_map_a, relay_url_a, _guard_a) = run_relay_server().await?;
+        let (relay_map_b, relay_url_b, _guard_b) = run_relay_server().await?;
+        let ep = Endpoint::builder()
+            .insecure_skip_relay_cert_verify(true)
+            .relay_mode(RelayMode::Custom(relay_map_a))
+            .bind()
+            .await?;
+        assert_eq!(ep.home_relay().initialized().await?, relay_url_a);
+
+        let relay_b = relay_map_b.get_node(&relay_url_b).unwrap();
+        assert!(ep.insert_relay(relay_b.as_ref().clone()).await.is_none());
+        assert!(ep.remove_relay(&relay_url_a).await.is_some());
+        assert!(ep.remove_relay(&relay_url_a).await.is_none());
+
+        // The removed relay is no longer used as the home relay.
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if ep.home_relay().get()? == vec![relay_url_b.clone()] {
+                    return Ok::<_, Error>(());
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .e()??;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn endpoint_remove_relay_during_net_report() -> Result {
+        let (relay_map_a, relay_url_a, _guard_a) = run_relay_server().await?;
+        let (relay_map_b, relay_url_b, _guard_b) = run_relay_server().await?;
+        let ep = Endpoint::builder()
+            .insecure_skip_relay_cert_verify(true)
+            .relay_mode(RelayMode::Custom(relay_map_a))
+            .bind()
+            .await?;
+        let mut home_relay = ep.home_relay().stream();
+        // Give the initial net_report time to start, it takes longer than this on localhost.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        if !ep.home_relay().get()?.is_empty() {
+            info!("initial net_report already finished, skipping test");
+            return Ok(());
+        }
+
+        // The initial net_report is still running and only knows about relay A.
+        let relay_b = relay_map_b.get_node(&relay_url_b).unwrap();
+        assert!(ep.insert_relay(relay_b.as_ref().clone()).await.is_none());
+        assert!(ep.remove_relay(&relay_url_a).await.is_some());
+
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while let Some(urls) = home_relay.next().await {
+                assert!(!urls.contains(&relay_url_a), "removed relay is home relay");
+                if urls == vec![relay_url_b.clone()] {
+                    break;
+                }
+            }
+        })
+        .await
+        .e()?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn endpoint_remove_only_relay() -> Result {
+        let (relay_map, relay_url, _guard) = run_relay_server().await?;
+        let ep = Endpoint::builder()
+            .insecure_skip_relay_cert_verify(true)
+            .relay_mode(RelayMode::Custom(relay_map))
+            .bind()
+            .await?;
+        assert_eq!(ep.home_relay().initialized().await?, relay_url);
+
+        assert!(ep.remove_relay(&relay_url).await.is_some());
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                if ep.home_relay().get()?.is_empty() {
+                    return Ok::<_, Error>(());
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await
+        .e()??;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn endpoint_remove_relay_is_not_shared() -> Result {
+        let (relay_map, relay_url, _guard) = run_relay_server().await?;
+        let ep_a = Endpoint::builder()
+            .insecure_skip_relay_cert_verify(true)
+            .relay_mode(RelayMode::Custom(relay_map.clone()))
+            .bind()
+            .await?;
+        let ep_b = Endpoint::builder()
+            .insecure_skip_relay_cert_verify(true)
+            .relay_mode(RelayMode::Custom(relay_map.clone()))
+            .bind()
+            .await?;
+        assert_eq!(ep_b.home_relay().initialized().await?, relay_url);
+
+        assert!(ep_a.remove_relay(&relay_url).await.is_some());
+        assert!(relay_map.contains_node(&relay_url));
+        assert_eq!(ep_b.home_relay().get()?, vec![relay_url.clone()]);
+        // The relay server is still used by the other endpoint.
+        assert!(ep_b.remove_relay(&relay_url).await.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn endpoint_portmapper_disabled() -> Result {
+        let enabled = Endpoint::builder()
+            .relay_mode(RelayMode::Disabled)
+            .bind()
+            .await?;
+        let disabled = Endpoint::builder()
+            .relay_mode(RelayMode::Disabled)
+            .portmapper_config(PortmapperConfig {
+                enable_upnp: false,
+                enable_pcp: false,
+                enable_nat_pmp: false,
+            })
+            .bind()
+            .await?;
+
+        // A finished mapping task either updates the external address or fails.
+        let mappings_done = |ep: &Endpoint| {
+            let metrics = &ep.metrics().portmapper;
+            metrics.external_address_updated.get() + metrics.mapping_failures.get()
+        };
+        let res = tokio::time::timeout(Duration::from_secs(10), async {
+            while mappings_done(&enabled) == 0 {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        })
+        .await;
+        if res.is_err() {
+            // Without a default gateway no mapping is ever attempted.
+            info!("no port mapping attempted with the default config, skipping");
+            return Ok(());
+        }
+
+        let metrics = &disabled.metrics().portmapper;
+        assert!(metrics.mapping_attempts.get() > 0);
+        assert_eq!(mappings_done(&disabled), 0);
+        let addrs = disabled.direct_addresses().initialized().await?;
+        assert!(
+            addrs
+                .iter()
+                .all(|addr| addr.typ != DirectAddrType::Portmapped)
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    #[traced_test]
+    async fn endpoint_advertised_addrs() -> Result {
+        let advertised: SocketAddr = "203.0.113.7:4433".parse().unwrap();
+        let ep = Endpoint::builder()
+            .relay_mode(RelayMode::Disabled)
+            .advertised_addrs([advertised])
+            .bind()
+            .await?;
+        let addrs = ep.direct_addresses().initialized().await?;
+        assert!(addrs.contains(&DirectAddr {
+            addr: advertised,
+            typ: DirectAddrType::Advertised,
+        }));
+        // The local addresses are still discovered.
+        assert!(addrs.iter().any(|addr| addr.typ == DirectAddrType::Local));
+
+        let node_addr = ep.node_addr().initialized().await?;
+        assert!(node_addr.direct_addresses.contains(&advertised));
+        Ok(())
+    }
+
     #[tokio::test]
     #[traced_test]
     async fn watch_net_report() -> Result {
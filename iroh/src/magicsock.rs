@@ -31,7 +31,7 @@ use std::{
 use bytes::Bytes;
 use data_encoding::HEXLOWER;
 use iroh_base::{NodeAddr, NodeId, PublicKey, RelayUrl, SecretKey};
-use iroh_relay::RelayMap;
+use iroh_relay::{RelayMap, RelayNode};
 use n0_future::{
     StreamExt,
     boxed::BoxStream,
@@ -65,7 +65,7 @@ use self::{
 #[cfg(not(wasm_browser))]
 use crate::dns::DnsResolver;
 #[cfg(any(test, feature = "test-utils"))]
-use crate::endpoint::PathSelection;
+use crate::endpoint::{NetworkConditions, PathSelection};
 #[cfg(not(wasm_browser))]
 use crate::net_report::{IpMappedAddr, QuicConfig};
 use crate::{
@@ -129,6 +129,14 @@ pub(crate) struct Options {
     #[cfg(not(wasm_browser))]
     pub(crate) dns_resolver: DnsResolver,
 
+    /// Which port mapping protocols to use.
+    #[cfg(not(wasm_browser))]
+    pub(crate) portmapper_config: portmapper::Config,
+
+    /// Addresses to always include in our direct addresses.
+    #[cfg(not(wasm_browser))]
+    pub(crate) advertised_addrs: Vec<SocketAddr>,
+
     /// Proxy configuration.
     pub(crate) proxy_url: Option<Url>,
 
@@ -145,6 +153,10 @@ pub(crate) struct Options {
     #[cfg(any(test, feature = "test-utils"))]
     pub(crate) path_selection: PathSelection,
 
+    /// Network conditions to simulate on received datagrams.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub(crate) network_conditions: Option<NetworkConditions>,
+
     pub(crate) metrics: EndpointMetrics,
 }
 
@@ -202,6 +214,9 @@ pub(crate) struct MagicSock {
     /// Currently bound IP addresses of all sockets
     #[cfg(not(wasm_browser))]
     ip_bind_addrs: Vec<SocketAddr>,
+    /// Explicitly configured addresses, always included in the direct addresses.
+    #[cfg(not(wasm_browser))]
+    advertised_addrs: Vec<SocketAddr>,
     /// The DNS resolver to be used in this magicsock.
     #[cfg(not(wasm_browser))]
     dns_resolver: DnsResolver,
@@ -217,6 +232,11 @@ pub(crate) struct MagicSock {
     /// Broadcast channel for listening to discovery updates.
     discovery_subscribers: DiscoverySubscribers,
 
+    /// The relay servers to use.
+    ///
+    /// The net_report client is given a copy of this before each run.
+    relay_map: RwLock<RelayMap>,
+
     /// Metrics
     pub(crate) metrics: EndpointMetrics,
 }
@@ -445,6 +465,43 @@ impl MagicSock {
             .ok();
     }
 
+    /// Inserts a relay server, replacing and returning any relay server with the same URL.
+    ///
+    /// This schedules a new net_report so the new relay server is considered for the home
+    /// relay.
+    pub(crate) async fn insert_relay(&self, node: RelayNode) -> Option<Arc<RelayNode>> {
+        let old = self.relay_map.write().expect("poisoned").insert(node);
+        self.relay_map_changed().await;
+        old
+    }
+
+    /// Removes a relay server, returning it if it was present.
+    ///
+    /// If this was the home relay, a new home relay is chosen by the next net_report,
+    /// which is scheduled immediately.
+    pub(crate) async fn remove_relay(&self, url: &RelayUrl) -> Option<Arc<RelayNode>> {
+        let old = self.relay_map.write().expect("poisoned").remove(url);
+        if old.is_some() {
+            self.relay_map_changed().await;
+        }
+        old
+    }
+
+    /// Returns a copy of the current relay servers.
+    fn relay_map(&self) -> RelayMap {
+        self.relay_map.read().expect("poisoned").clone()
+    }
+
+    async fn relay_map_changed(&self) {
+        self.actor_sender
+            .send(ActorMessage::ScheduleDirectAddrUpdate(
+                UpdateReason::RelayMapChange,
+                None,
+            ))
+            .await
+            .ok();
+    }
+
     /// Returns a reference to the subscribers channel for discovery events.
     pub(crate) fn discovery_subscribers(&self) -> &DiscoverySubscribers {
         &self.discovery_subscribers
@@ -1083,7 +1140,6 @@ struct DirectAddrUpdateState {
     port_mapper: portmapper::Client,
     /// The prober that discovers local network conditions, including the closest relay relay and NAT mappings.
     net_reporter: Arc<AsyncMutex<net_report::Client>>,
-    relay_map: RelayMap,
     run_done: mpsc::Sender<()>,
 }
 
@@ -1097,11 +1153,13 @@ enum UpdateReason {
     PortmapUpdated,
     LinkChangeMajor,
     LinkChangeMinor,
+    RelayMapChange,
 }
 
 impl UpdateReason {
+    /// Whether this needs a full net_report, ignoring any previous reports.
     fn is_major(self) -> bool {
-        matches!(self, Self::LinkChangeMajor)
+        matches!(self, Self::LinkChangeMajor | Self::RelayMapChange)
     }
 }
 
@@ -1110,7 +1168,6 @@ impl DirectAddrUpdateState {
         msock: Arc<MagicSock>,
         #[cfg(not(wasm_browser))] port_mapper: portmapper::Client,
         net_reporter: Arc<AsyncMutex<net_report::Client>>,
-        relay_map: RelayMap,
         run_done: mpsc::Sender<()>,
     ) -> Self {
         DirectAddrUpdateState {
@@ -1119,7 +1176,6 @@ impl DirectAddrUpdateState {
             port_mapper,
             net_reporter,
             msock,
-            relay_map,
             run_done,
         }
     }
@@ -1167,11 +1223,13 @@ impl DirectAddrUpdateState {
             debug!("skipping net_report, socket is shutting down");
             return;
         }
-        if self.relay_map.is_empty() {
+        let relay_map = self.msock.relay_map();
+        if relay_map.is_empty() {
             debug!("skipping net_report, empty RelayMap");
             self.msock.net_report.set((None, why)).ok();
             return;
         }
+        net_reporter.set_relay_map(relay_map);
 
         debug!("requesting net_report report");
         let msock = self.msock.clone();
@@ -1235,12 +1293,18 @@ impl Handle {
             discovery_user_data,
             #[cfg(not(wasm_browser))]
             dns_resolver,
+            #[cfg(not(wasm_browser))]
+            portmapper_config,
+            #[cfg(not(wasm_browser))]
+            advertised_addrs,
             proxy_url,
             server_config,
             #[cfg(any(test, feature = "test-utils"))]
             insecure_skip_relay_cert_verify,
             #[cfg(any(test, feature = "test-utils"))]
             path_selection,
+            #[cfg(any(test, feature = "test-utils"))]
+            network_conditions,
             metrics,
         } = opts;
 
@@ -1248,7 +1312,7 @@ impl Handle {
 
         #[cfg(not(wasm_browser))]
         let (ip_transports, port_mapper) =
-            bind_ip(addr_v4, addr_v6, &metrics).context(BindSocketsSnafu)?;
+            bind_ip(addr_v4, addr_v6, portmapper_config, &metrics).context(BindSocketsSnafu)?;
 
         let ip_mapped_addrs = IpMappedAddresses::default();
 
@@ -1285,6 +1349,8 @@ impl Handle {
         let transports = Transports::new(ip_transports, relay_transports);
         #[cfg(wasm_browser)]
         let transports = Transports::new(relay_transports);
+        #[cfg(any(test, feature = "test-utils"))]
+        let transports = transports.with_network_conditions(network_conditions);
 
         let (disco, disco_receiver) = DiscoState::new(secret_encryption_key);
 
@@ -1304,10 +1370,13 @@ impl Handle {
             #[cfg(not(wasm_browser))]
             dns_resolver: dns_resolver.clone(),
             discovery_subscribers: DiscoverySubscribers::new(),
+            relay_map: RwLock::new(relay_map.clone()),
             metrics: metrics.clone(),
             local_addrs_watch: transports.local_addrs_watch(),
             #[cfg(not(wasm_browser))]
             ip_bind_addrs: transports.ip_bind_addrs(),
+            #[cfg(not(wasm_browser))]
+            advertised_addrs,
         });
 
         let mut endpoint_config = quinn::EndpointConfig::default();
@@ -1380,7 +1449,6 @@ impl Handle {
             #[cfg(not(wasm_browser))]
             port_mapper,
             Arc::new(AsyncMutex::new(net_reporter)),
-            relay_map,
             direct_addr_done_tx,
         );
 
@@ -1672,10 +1740,11 @@ struct Actor {
 fn bind_ip(
     addr_v4: SocketAddrV4,
     addr_v6: Option<SocketAddrV6>,
+    portmapper_config: portmapper::Config,
     metrics: &EndpointMetrics,
 ) -> io::Result<(Vec<IpTransport>, portmapper::Client)> {
     let port_mapper =
-        portmapper::Client::with_metrics(Default::default(), metrics.portmapper.clone());
+        portmapper::Client::with_metrics(portmapper_config, metrics.portmapper.clone());
 
     let v4 = Arc::new(bind_with_fallback(SocketAddr::V4(addr_v4))?);
     let ip4_port = v4.local_addr()?.port();
@@ -1966,6 +2035,7 @@ impl Actor {
     /// Updates the [`DiscoveredDirectAddrs`] of this [`MagicSock`] with the current set of
     /// direct addresses from:
     ///
+    /// - The explicitly advertised addresses.
     /// - The portmapper.
     /// - A net_report report.
     /// - The local interfaces IP addresses.
@@ -1981,7 +2051,12 @@ impl Actor {
         // DirectAddr from each entry.
         let mut addrs: BTreeMap<SocketAddr, DirectAddrType> = BTreeMap::new();
 
-        // First add PortMapper provided addresses.
+        // First add the addresses configured by the user.
+        for addr in &self.msock.advertised_addrs {
+            addrs.entry(*addr).or_insert(DirectAddrType::Advertised);
+        }
+
+        // Next add PortMapper provided addresses.
         let maybe_port_mapped = *portmap_watcher.borrow();
         if let Some(portmap_ext) = maybe_port_mapped.map(SocketAddr::V4) {
             addrs
@@ -2119,14 +2194,31 @@ impl Actor {
     fn handle_net_report_report(&mut self, mut report: Option<net_report::Report>) {
         if let Some(ref mut r) = report {
             self.msock.ipv6_reported.store(r.udp_v6, Ordering::Relaxed);
+            let relay_map = self.msock.relay_map();
+            if r.preferred_relay
+                .as_ref()
+                .is_some_and(|url| !relay_map.contains_node(url))
+            {
+                // The report was started before this relay server was removed.
+                r.preferred_relay = None;
+            }
             if r.preferred_relay.is_none() {
-                if let Some(my_relay) = self.msock.my_relay() {
+                // Only keep the current home relay if it was not removed from the relay map.
+                if let Some(my_relay) = self
+                    .msock
+                    .my_relay()
+                    .filter(|url| relay_map.contains_node(url))
+                {
                     r.preferred_relay.replace(my_relay);
                 }
             }
 
             // Notify all transports
             self.network_change_sender.on_network_change(r);
+        } else if self.msock.my_relay().is_some() && self.msock.relay_map().is_empty() {
+            // No net_report runs without relay servers, so clear the home relay here.
+            self.network_change_sender
+                .on_network_change(&net_report::Report::default());
         }
 
         #[cfg(not(wasm_browser))]
@@ -2394,6 +2486,12 @@ pub enum DirectAddrType {
     /// configure the router to forward this port to the iroh node.  This indicates a
     /// situation like this, which still uses QAD to discover the public address.
     Qad4LocalPort,
+    /// An address explicitly configured to be advertised.
+    ///
+    /// This is used for addresses which are known to be reachable, e.g. a stable public IP
+    /// address or a port manually forwarded on the router, see
+    /// [`Builder::advertised_addrs`](crate::endpoint::Builder::advertised_addrs).
+    Advertised,
 }
 
 impl Display for DirectAddrType {
@@ -2404,6 +2502,7 @@ impl Display for DirectAddrType {
             DirectAddrType::Qad => write!(f, "qad"),
             DirectAddrType::Portmapped => write!(f, "portmap"),
             DirectAddrType::Qad4LocalPort => write!(f, "qad4localport"),
+            DirectAddrType::Advertised => write!(f, "advertised"),
         }
     }
 }
@@ -2448,11 +2547,15 @@ mod tests {
                 discovery: None,
                 proxy_url: None,
                 dns_resolver: DnsResolver::new(),
+                portmapper_config: Default::default(),
+                advertised_addrs: Vec::new(),
                 server_config,
                 #[cfg(any(test, feature = "test-utils"))]
                 insecure_skip_relay_cert_verify: false,
                 #[cfg(any(test, feature = "test-utils"))]
                 path_selection: PathSelection::default(),
+                #[cfg(any(test, feature = "test-utils"))]
+                network_conditions: None,
                 discovery_user_data: None,
                 metrics: Default::default(),
             }
@@ -2980,10 +3083,13 @@ mod tests {
             discovery: None,
             discovery_user_data: None,
             dns_resolver,
+            portmapper_config: Default::default(),
+            advertised_addrs: Vec::new(),
             proxy_url: None,
             server_config,
             insecure_skip_relay_cert_verify: false,
             path_selection: PathSelection::default(),
+            network_conditions: None,
             metrics: Default::default(),
         };
         let msock = MagicSock::spawn(opts).await?;
@@ -1413,7 +1413,7 @@ impl RemoteInfo {
                 }
             }
         }
-        sources.sort_by(|a, b| b.1.cmp(&a.1));
+        sources.sort_by_key(|s| std::cmp::Reverse(s.1));
         sources
     }
 }
@@ -1708,8 +1708,8 @@ mod tests {
 
     fn remove_non_deterministic_fields(infos: &mut [RemoteInfo]) {
         for info in infos.iter_mut() {
-            if info.relay_url.is_some() {
-                info.relay_url.as_mut().unwrap().last_alive = None;
+            if let Some(relay_url) = info.relay_url.as_mut() {
+                relay_url.last_alive = None;
             }
         }
     }
@@ -15,13 +15,19 @@ use tracing::{error, trace, warn};
 #[cfg(not(wasm_browser))]
 mod ip;
 mod relay;
+#[cfg(any(test, feature = "test-utils"))]
+mod simulated;
 
 #[cfg(not(wasm_browser))]
 pub(crate) use self::ip::IpTransport;
 #[cfg(not(wasm_browser))]
 use self::ip::{IpNetworkChangeSender, IpSender};
 pub(crate) use self::relay::{RelayActorConfig, RelayTransport};
+#[cfg(any(test, feature = "test-utils"))]
+use self::simulated::NetworkSimulator;
 use super::MagicSock;
+#[cfg(any(test, feature = "test-utils"))]
+use crate::endpoint::NetworkConditions;
 use crate::net_report::Report;
 
 /// Manages the different underlying data transports that the magicsock
@@ -33,6 +39,8 @@ pub(crate) struct Transports {
     relay: Vec<RelayTransport>,
 
     poll_recv_counter: AtomicUsize,
+    #[cfg(any(test, feature = "test-utils"))]
+    network_simulator: Option<NetworkSimulator>,
 }
 
 #[cfg(not(wasm_browser))]
@@ -67,9 +75,21 @@ impl Transports {
             ip,
             relay,
             poll_recv_counter: Default::default(),
+            #[cfg(any(test, feature = "test-utils"))]
+            network_simulator: None,
         }
     }
 
+    /// Simulates the given network conditions on all received datagrams.
+    #[cfg(any(test, feature = "test-utils"))]
+    pub(crate) fn with_network_conditions(
+        mut self,
+        network_conditions: Option<NetworkConditions>,
+    ) -> Self {
+        self.network_simulator = network_conditions.map(NetworkSimulator::new);
+        self
+    }
+
     pub(crate) fn poll_recv(
         &mut self,
         cx: &mut Context,
@@ -83,7 +103,11 @@ impl Transports {
         }
 
         let mut source_addrs = vec![Addr::default(); metas.len()];
-        match self.inner_poll_recv(cx, bufs, metas, &mut source_addrs)? {
+        #[cfg(any(test, feature = "test-utils"))]
+        let res = self.simulated_poll_recv(cx, bufs, metas, &mut source_addrs)?;
+        #[cfg(not(any(test, feature = "test-utils")))]
+        let res = self.inner_poll_recv(cx, bufs, metas, &mut source_addrs)?;
+        match res {
             Poll::Pending | Poll::Ready(0) => Poll::Pending,
             Poll::Ready(n) => {
                 msock.process_datagrams(&mut bufs[..n], &mut metas[..n], &source_addrs[..n]);
@@ -92,6 +116,25 @@ impl Transports {
         }
     }
 
+    /// Like [`Self::inner_poll_recv`], but applies the simulated network conditions if any.
+    #[cfg(any(test, feature = "test-utils"))]
+    fn simulated_poll_recv(
+        &mut self,
+        cx: &mut Context,
+        bufs: &mut [IoSliceMut<'_>],
+        metas: &mut [quinn_udp::RecvMeta],
+        source_addrs: &mut [Addr],
+    ) -> Poll<io::Result<usize>> {
+        let Some(mut simulator) = self.network_simulator.take() else {
+            return self.inner_poll_recv(cx, bufs, metas, source_addrs);
+        };
+        let res = simulator.poll_recv(cx, bufs, metas, source_addrs, |cx, bufs, metas, addrs| {
+            self.inner_poll_recv(cx, bufs, metas, addrs)
+        });
+        self.network_simulator = Some(simulator);
+        res
+    }
+
     /// Tries to recv data, on all available transports.
     fn inner_poll_recv(
         &mut self,
@@ -620,6 +620,7 @@ impl ActiveRelayActor {
                     self.reset_inactive_timeout();
                     // TODO(frando): can we avoid the clone here?
                     let metrics = self.metrics.clone();
+                    #[allow(clippy::result_large_err)]
                     let packet_iter = send_datagrams_buf.drain(..).map(|item| {
                         metrics.send_relay.inc_by(item.datagrams.contents.len() as _);
                         Ok(ClientToRelayMsg::Datagrams {
@@ -1005,14 +1006,18 @@ impl RelayActor {
             // On change, notify all currently connected relay servers and
             // start connecting to our home relay if we are not already.
             info!("home is now relay {}, was {:?}", relay_url, old_relay);
-            self.set_home_relay(relay_url).await;
+            self.set_home_relay(Some(relay_url)).await;
+        } else {
+            // E.g. the home relay was removed from the relay map.
+            info!("no home relay, was {:?}", old_relay);
+            self.set_home_relay(None).await;
         }
     }
 
-    async fn set_home_relay(&mut self, home_url: RelayUrl) {
-        let home_url_ref = &home_url;
+    async fn set_home_relay(&mut self, home_url: Option<RelayUrl>) {
+        let home_url_ref = home_url.as_ref();
         n0_future::join_all(self.active_relays.iter().map(|(url, handle)| async move {
-            let is_preferred = url == home_url_ref;
+            let is_preferred = Some(url) == home_url_ref;
             handle
                 .inbox_addr
                 .send(ActiveRelayMessage::SetHomeRelay(is_preferred))
@@ -1021,7 +1026,9 @@ impl RelayActor {
         }))
         .await;
         // Ensure we have an ActiveRelayActor for the current home relay.
-        self.active_relay_handle(home_url);
+        if let Some(home_url) = home_url {
+            self.active_relay_handle(home_url);
+        }
     }
 
     /// Returns the handle for the [`ActiveRelayActor`] to reach `remote_node`.
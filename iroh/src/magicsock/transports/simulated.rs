@@ -0,0 +1,224 @@
+//! Simulation of adverse network conditions for testing.
+
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    future::Future,
+    io::{self, IoSliceMut},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use n0_future::time::{self, Duration, Instant, Sleep};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use tracing::{trace, warn};
+
+use super::Addr;
+use crate::endpoint::NetworkConditions;
+
+/// Applies [`NetworkConditions`] to the datagrams received by the transports.
+///
+/// Received datagrams are split into their individual segments, each of which is either
+/// dropped or held back until its simulated delivery time.
+#[derive(Debug)]
+pub(crate) struct NetworkSimulator {
+    latency: Duration,
+    jitter: Duration,
+    loss: f64,
+    rng: StdRng,
+    /// The datagrams which are not yet delivered, earliest delivery time first.
+    queue: BinaryHeap<Reverse<DelayedDatagram>>,
+    /// Used to keep the delivery order stable for equal delivery times.
+    next_seq: u64,
+    /// Wakes up the receiver once the earliest datagram in the queue is due.
+    timer: Option<(Instant, Pin<Box<Sleep>>)>,
+}
+
+#[derive(Debug)]
+struct DelayedDatagram {
+    deliver_at: Instant,
+    seq: u64,
+    contents: Vec<u8>,
+    meta: quinn_udp::RecvMeta,
+    source_addr: Addr,
+}
+
+impl PartialEq for DelayedDatagram {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for DelayedDatagram {}
+
+impl PartialOrd for DelayedDatagram {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for DelayedDatagram {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.deliver_at, self.seq).cmp(&(other.deliver_at, other.seq))
+    }
+}
+
+impl NetworkSimulator {
+    pub(crate) fn new(conditions: NetworkConditions) -> Self {
+        let rng = match conditions.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Self {
+            latency: conditions.latency,
+            jitter: conditions.jitter,
+            loss: normalize_loss(conditions.loss),
+            rng,
+            queue: Default::default(),
+            next_seq: 0,
+            timer: None,
+        }
+    }
+
+    /// Receives datagrams using `poll_recv`, but only returns them once they are due.
+    ///
+    /// The `bufs`, `metas` and `source_addrs` are used as scratch space to receive new
+    /// datagrams before they are filled with the datagrams to deliver.
+    pub(crate) fn poll_recv(
+        &mut self,
+        cx: &mut Context,
+        bufs: &mut [IoSliceMut<'_>],
+        metas: &mut [quinn_udp::RecvMeta],
+        source_addrs: &mut [Addr],
+        mut poll_recv: impl FnMut(
+            &mut Context,
+            &mut [IoSliceMut<'_>],
+            &mut [quinn_udp::RecvMeta],
+            &mut [Addr],
+        ) -> Poll<io::Result<usize>>,
+    ) -> Poll<io::Result<usize>> {
+        // Drain the transports, this also makes sure we are woken up for new datagrams.
+        loop {
+            match poll_recv(cx, bufs, metas, source_addrs)? {
+                Poll::Pending | Poll::Ready(0) => break,
+                Poll::Ready(n) => {
+                    for ((buf, meta), source_addr) in bufs
+                        .iter()
+                        .zip(metas.iter())
+                        .zip(source_addrs.iter())
+                        .take(n)
+                    {
+                        self.enqueue(&buf[..meta.len], meta, source_addr);
+                    }
+                }
+            }
+        }
+
+        loop {
+            let n = self.deliver(bufs, metas, source_addrs);
+            if n > 0 {
+                return Poll::Ready(Ok(n));
+            }
+            let Some(Reverse(next)) = self.queue.peek() else {
+                self.timer = None;
+                return Poll::Pending;
+            };
+            let deliver_at = next.deliver_at;
+            let timer = match self.timer {
+                Some((deadline, ref mut timer)) if deadline == deliver_at => timer,
+                _ => {
+                    let timer = Box::pin(time::sleep_until(deliver_at));
+                    &mut self.timer.insert((deliver_at, timer)).1
+                }
+            };
+            if timer.as_mut().poll(cx).is_pending() {
+                return Poll::Pending;
+            }
+        }
+    }
+
+    /// Queues the individual datagrams contained in `contents`, unless they are lost.
+    fn enqueue(&mut self, contents: &[u8], meta: &quinn_udp::RecvMeta, source_addr: &Addr) {
+        let stride = if meta.stride == 0 {
+            contents.len()
+        } else {
+            meta.stride
+        };
+        let now = Instant::now();
+        for datagram in contents.chunks(stride.max(1)) {
+            if self.rng.gen_bool(self.loss) {
+                trace!(len = datagram.len(), "simulated network: dropping datagram");
+                continue;
+            }
+            let jitter = if self.jitter.is_zero() {
+                Duration::ZERO
+            } else {
+                self.rng.gen_range(Duration::ZERO..=self.jitter)
+            };
+            let meta = quinn_udp::RecvMeta {
+                len: datagram.len(),
+                stride: datagram.len(),
+                ..*meta
+            };
+            self.queue.push(Reverse(DelayedDatagram {
+                deliver_at: now + self.latency + jitter,
+                seq: self.next_seq,
+                contents: datagram.to_vec(),
+                meta,
+                source_addr: source_addr.clone(),
+            }));
+            self.next_seq += 1;
+        }
+    }
+
+    /// Fills the buffers with the datagrams that are due, returns how many were filled.
+    fn deliver(
+        &mut self,
+        bufs: &mut [IoSliceMut<'_>],
+        metas: &mut [quinn_udp::RecvMeta],
+        source_addrs: &mut [Addr],
+    ) -> usize {
+        let now = Instant::now();
+        let mut n = 0;
+        while n < bufs.len() {
+            match self.queue.peek() {
+                Some(Reverse(next)) if next.deliver_at <= now => {}
+                _ => break,
+            }
+            let Reverse(datagram) = self.queue.pop().expect("checked");
+            let len = datagram.contents.len();
+            if len > bufs[n].len() {
+                warn!(len, "simulated network: datagram exceeds receive buffer");
+                continue;
+            }
+            bufs[n][..len].copy_from_slice(&datagram.contents);
+            metas[n] = datagram.meta;
+            source_addrs[n] = datagram.source_addr;
+            n += 1;
+        }
+        n
+    }
+}
+
+/// Clamps the loss probability to `0.0..=1.0`, treating NaN as no loss.
+fn normalize_loss(loss: f64) -> f64 {
+    if loss.is_nan() {
+        warn!("simulated network: loss is NaN, not dropping any datagrams");
+        return 0.0;
+    }
+    loss.clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_loss() {
+        assert_eq!(normalize_loss(f64::NAN), 0.0);
+        assert_eq!(normalize_loss(f64::INFINITY), 1.0);
+        assert_eq!(normalize_loss(f64::NEG_INFINITY), 0.0);
+        assert_eq!(normalize_loss(-0.5), 0.0);
+        assert_eq!(normalize_loss(0.25), 0.25);
+    }
+}
@@ -250,6 +250,11 @@ impl Client {
         }
     }
 
+    /// Sets the relay servers used by the next reports.
+    pub(crate) fn set_relay_map(&mut self, relay_map: RelayMap) {
+        self.relay_map = relay_map;
+    }
+
     /// Generates a [`Report`].
     ///
     /// Look at [`Options`] for the different configuration options.
@@ -10,6 +10,7 @@ use iroh_relay::{
         TlsConfig,
     },
 };
+pub use test_network::{SpawnTestNetworkError, TestNetwork, TestNetworkBuilder};
 use tokio::sync::oneshot;
 
 /// A drop guard to clean up test infrastructure.
@@ -80,6 +81,235 @@ pub async fn run_relay_server_with(quic: bool) -> Result<(RelayMap, RelayUrl, Se
     Ok((n, url, server))
 }
 
+pub(crate) mod test_network {
+    use iroh_base::NodeId;
+    use iroh_relay::{RelayMap, server::Server};
+    use n0_future::task::JoinSet;
+    use n0_watcher::Watcher;
+    use nested_enum_utils::common_fields;
+    use snafu::{ResultExt, Snafu};
+
+    use super::run_relay_server;
+    use crate::{
+        Endpoint, RelayMode,
+        discovery::static_provider::StaticProvider,
+        endpoint::{BindError, ConnectError, Connection, NetworkConditions},
+    };
+
+    /// A number of endpoints running in-process, for multi-node tests.
+    ///
+    /// All endpoints use the same relay server running on localhost and know each other's
+    /// addresses, so they can connect to each other using only the [`NodeId`].  The
+    /// [`NetworkConditions`] are applied to every endpoint.
+    ///
+    /// Once the struct is dropped the relay server shuts down, the endpoints have to be
+    /// closed separately, e.g. using [`TestNetwork::close`].
+    #[derive(Debug)]
+    pub struct TestNetwork {
+        endpoints: Vec<Endpoint>,
+        relay_map: RelayMap,
+        _relay_server: Server,
+    }
+
+    /// Builder for a [`TestNetwork`].
+    #[derive(Debug, Default)]
+    pub struct TestNetworkBuilder {
+        nodes: usize,
+        alpns: Vec<Vec<u8>>,
+        network_conditions: Option<NetworkConditions>,
+    }
+
+    #[allow(missing_docs)]
+    #[common_fields({
+        backtrace: Option<snafu::Backtrace>,
+        #[snafu(implicit)]
+        span_trace: n0_snafu::SpanTrace,
+    })]
+    #[derive(Debug, Snafu)]
+    #[non_exhaustive]
+    pub enum SpawnTestNetworkError {
+        #[snafu(display("Failed to spawn the relay server"))]
+        RelayServer {
+            source: iroh_relay::server::SpawnError,
+        },
+        #[snafu(display("Failed to bind endpoint"))]
+        Bind { source: BindError },
+        #[snafu(display("Endpoint closed before its address was known"))]
+        Address { source: n0_watcher::Disconnected },
+    }
+
+    impl TestNetworkBuilder {
+        /// Sets the number of endpoints to spawn.
+        pub fn nodes(mut self, nodes: usize) -> Self {
+            self.nodes = nodes;
+            self
+        }
+
+        /// Sets the ALPN protocols all endpoints accept.
+        pub fn alpns(mut self, alpns: Vec<Vec<u8>>) -> Self {
+            self.alpns = alpns;
+            self
+        }
+
+        /// Sets the network conditions to simulate on all endpoints.
+        ///
+        /// If the conditions have a seed, each endpoint uses a different seed derived from
+        /// it, so the simulated loss and jitter are reproducible.  The endpoints still use
+        /// real sockets and timers, so the network as a whole is not deterministic.
+        ///
+        /// By default no network conditions are simulated.
+        pub fn network_conditions(mut self, network_conditions: NetworkConditions) -> Self {
+            self.network_conditions = Some(network_conditions);
+            self
+        }
+
+        /// Spawns the relay server and binds all endpoints.
+        ///
+        /// Returns once the addresses of all endpoints are known.
+        pub async fn spawn(self) -> Result<TestNetwork, SpawnTestNetworkError> {
+            let (relay_map, _relay_url, relay_server) =
+                run_relay_server().await.context(RelayServerSnafu)?;
+            let discovery = StaticProvider::new();
+
+            let mut endpoints = Vec::with_capacity(self.nodes);
+            for i in 0..self.nodes {
+                let mut builder = Endpoint::builder()
+                    .relay_mode(RelayMode::Custom(relay_map.clone()))
+                    .insecure_skip_relay_cert_verify(true)
+                    .alpns(self.alpns.clone())
+                    .discovery(discovery.clone());
+                if let Some(network_conditions) = &self.network_conditions {
+                    builder = builder.network_conditions(NetworkConditions {
+                        seed: network_conditions
+                            .seed
+                            .map(|seed| seed.wrapping_add(i as u64)),
+                        ..network_conditions.clone()
+                    });
+                }
+                let endpoint = builder.bind().await.context(BindSnafu)?;
+                endpoints.push(endpoint);
+            }
+
+            for endpoint in &endpoints {
+                let node_addr = endpoint
+                    .node_addr()
+                    .initialized()
+                    .await
+                    .context(AddressSnafu)?;
+                discovery.add_node_info(node_addr);
+            }
+
+            Ok(TestNetwork {
+                endpoints,
+                relay_map,
+                _relay_server: relay_server,
+            })
+        }
+    }
+
+    impl TestNetwork {
+        /// Returns a builder for a [`TestNetwork`].
+        pub fn builder() -> TestNetworkBuilder {
+            TestNetworkBuilder::default()
+        }
+
+        /// Returns all endpoints of the network.
+        pub fn endpoints(&self) -> &[Endpoint] {
+            &self.endpoints
+        }
+
+        /// Returns the endpoint with the given index.
+        ///
+        /// # Panics
+        ///
+        /// If `index` is out of bounds.
+        pub fn endpoint(&self, index: usize) -> &Endpoint {
+            &self.endpoints[index]
+        }
+
+        /// Returns the [`NodeId`]s of all endpoints.
+        pub fn node_ids(&self) -> Vec<NodeId> {
+            self.endpoints.iter().map(|ep| ep.node_id()).collect()
+        }
+
+        /// Returns the [`RelayMap`] of the relay server used by all endpoints.
+        pub fn relay_map(&self) -> &RelayMap {
+            &self.relay_map
+        }
+
+        /// Connects the endpoint at index `from` to the endpoint at index `to`.
+        ///
+        /// The accepting side has to be handled by the caller, e.g. by accepting on
+        /// [`TestNetwork::endpoint`] or by running a [`Router`] on it.
+        ///
+        /// [`Router`]: crate::protocol::Router
+        pub async fn connect(
+            &self,
+            from: usize,
+            to: usize,
+            alpn: &[u8],
+        ) -> Result<Connection, ConnectError> {
+            let node_id = self.endpoints[to].node_id();
+            self.endpoints[from].connect(node_id, alpn).await
+        }
+
+        /// Closes all endpoints.
+        pub async fn close(self) {
+            let mut tasks = JoinSet::new();
+            for endpoint in self.endpoints {
+                tasks.spawn(async move { endpoint.close().await });
+            }
+            tasks.join_all().await;
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use n0_snafu::{Result, ResultExt};
+        use tracing_test::traced_test;
+
+        use super::TestNetwork;
+        use crate::endpoint::NetworkConditions;
+
+        const TEST_ALPN: &[u8] = b"n0/iroh/test-network";
+
+        #[tokio::test]
+        #[traced_test]
+        async fn test_network_connect_all() -> Result {
+            let network = TestNetwork::builder()
+                .nodes(3)
+                .alpns(vec![TEST_ALPN.to_vec()])
+                .network_conditions(NetworkConditions {
+                    seed: Some(0),
+                    ..Default::default()
+                })
+                .spawn()
+                .await?;
+            assert_eq!(network.node_ids().len(), 3);
+
+            for ep in network.endpoints() {
+                let ep = ep.clone();
+                tokio::spawn(async move {
+                    while let Some(incoming) = ep.accept().await {
+                        if let Ok(conn) = incoming.await {
+                            conn.closed().await;
+                        }
+                    }
+                });
+            }
+
+            for (from, to) in [(0, 1), (1, 2), (2, 0)] {
+                let conn = network.connect(from, to, TEST_ALPN).await?;
+                assert_eq!(conn.remote_node_id().e()?, network.endpoint(to).node_id());
+                conn.close(0u32.into(), b"done");
+            }
+
+            network.close().await;
+            Ok(())
+        }
+    }
+}
+
 pub(crate) mod dns_and_pkarr_servers {
     use std::{net::SocketAddr, time::Duration};
 